@@ -11,52 +11,211 @@ use core::convert::From;
 use core::fmt;
 #[cfg(not(target_env = "sgx"))]
 use std::{io, error};
+#[cfg(all(feature = "std", not(target_env = "sgx")))]
+use std::boxed::Box;
 
-// A randomly-chosen 16-bit prefix for our codes
-pub(crate) const CODE_PREFIX: u32 = 0x57f40000;
-const CODE_UNKNOWN: u32 = CODE_PREFIX | 0;
-const CODE_UNAVAILABLE: u32 = CODE_PREFIX | 1;
+// Codes below `INTERNAL_START` are reserved for OS errors (i.e. positive
+// `i32` values). Codes in the range `[INTERNAL_START, CUSTOM_START)` are
+// reserved for getrandom's own use, and codes at or above `CUSTOM_START`
+// are free for users of the crate to define their own error codes.
+pub(crate) const INTERNAL_START: u32 = 1 << 31;
+pub(crate) const CUSTOM_START: u32 = (1 << 31) + (1 << 30);
+const CODE_UNKNOWN: u32 = INTERNAL_START | 0;
+const CODE_UNAVAILABLE: u32 = INTERNAL_START | 1;
+const CODE_RDRAND_FAILURE: u32 = INTERNAL_START | 2;
+const CODE_WEB_CRYPTO_UNAVAILABLE: u32 = INTERNAL_START | 3;
+const CODE_DEV_RANDOM_OPEN_FAILED: u32 = INTERNAL_START | 4;
+const CODE_UNEXPECTED_SHORT_READ: u32 = INTERNAL_START | 5;
+const CODE_TOO_MANY_EINTR: u32 = INTERNAL_START | 6;
 
 /// An unknown error.
-/// 
-/// This is the following constant: 57F40000 (hex) / 1475608576 (decimal).
-pub const ERROR_UNKNOWN: Error = Error(unsafe {
+///
+/// This is the following constant: 80000000 (hex) / 2147483648 (decimal).
+pub const ERROR_UNKNOWN: Error = Error::new(unsafe {
     NonZeroU32::new_unchecked(CODE_UNKNOWN)
 });
 
 /// No generator is available.
-/// 
-/// This is the following constant: 57F40001 (hex) / 1475608577 (decimal).
-pub const ERROR_UNAVAILABLE: Error = Error(unsafe {
+///
+/// This is the following constant: 80000001 (hex) / 2147483649 (decimal).
+pub const ERROR_UNAVAILABLE: Error = Error::new(unsafe {
     NonZeroU32::new_unchecked(CODE_UNAVAILABLE)
 });
 
+/// The RDRAND instruction failed after 10 retries.
+///
+/// This is the following constant: 80000002 (hex) / 2147483650 (decimal).
+pub const ERROR_RDRAND_FAILURE: Error = Error::new(unsafe {
+    NonZeroU32::new_unchecked(CODE_RDRAND_FAILURE)
+});
+
+/// The Web Crypto API is unavailable.
+///
+/// This is the following constant: 80000003 (hex) / 2147483651 (decimal).
+pub const ERROR_WEB_CRYPTO_UNAVAILABLE: Error = Error::new(unsafe {
+    NonZeroU32::new_unchecked(CODE_WEB_CRYPTO_UNAVAILABLE)
+});
+
+/// Opening `/dev/random` failed.
+///
+/// This is the following constant: 80000004 (hex) / 2147483652 (decimal).
+pub const ERROR_DEV_RANDOM_OPEN_FAILED: Error = Error::new(unsafe {
+    NonZeroU32::new_unchecked(CODE_DEV_RANDOM_OPEN_FAILED)
+});
+
+/// A read from the OS entropy source returned fewer bytes than requested.
+///
+/// This is the following constant: 80000005 (hex) / 2147483653 (decimal).
+pub const ERROR_UNEXPECTED_SHORT_READ: Error = Error::new(unsafe {
+    NonZeroU32::new_unchecked(CODE_UNEXPECTED_SHORT_READ)
+});
+
+/// A syscall returned `EINTR` too many times in a row.
+///
+/// This is the following constant: 80000006 (hex) / 2147483654 (decimal).
+pub const ERROR_TOO_MANY_EINTR: Error = Error::new(unsafe {
+    NonZeroU32::new_unchecked(CODE_TOO_MANY_EINTR)
+});
+
+// Static messages for the internal error codes above, looked up by both
+// `msg()` and the `Debug`/`Display` impls.
+const INTERNAL_ERRORS: &[(u32, &str)] = &[
+    (CODE_UNKNOWN, "getrandom: unknown error"),
+    (CODE_UNAVAILABLE, "getrandom: unavailable"),
+    (CODE_RDRAND_FAILURE, "getrandom: RDRAND failed after 10 retries"),
+    (CODE_WEB_CRYPTO_UNAVAILABLE, "getrandom: Web Crypto API is unavailable"),
+    (CODE_DEV_RANDOM_OPEN_FAILED, "getrandom: failed to open /dev/random"),
+    (CODE_UNEXPECTED_SHORT_READ, "getrandom: unexpected short read"),
+    (CODE_TOO_MANY_EINTR, "getrandom: too many retries after EINTR"),
+];
+
 /// The error type.
-/// 
-/// This type is small and no-std compatible.
+///
+/// This type is small and no-std compatible. With the `std` feature enabled,
+/// it can additionally carry a boxed "cause" (e.g. the original `io::Error`
+/// an OS failure was converted from), at the expense of no longer being a
+/// fixed 4-byte `Copy` type. The `error` module used to store that cause is
+/// unavailable on `sgx` targets (see the `not(target_env = "sgx")` imports
+/// above), so on those targets `Error` keeps the plain `NonZeroU32`
+/// representation even when `std` is enabled.
+#[cfg(any(not(feature = "std"), target_env = "sgx"))]
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub struct Error(NonZeroU32);
 
+/// The error type.
+///
+/// This variant is only defined with the `std` feature enabled, and carries
+/// an optional boxed cause alongside its code. The boxed cause
+/// means this variant cannot be `Copy`/`Clone`; `PartialEq`/`Eq` are still
+/// implemented manually, comparing only the `code` (the cause is ignored).
+#[cfg(all(feature = "std", not(target_env = "sgx")))]
+pub struct Error {
+    code: NonZeroU32,
+    cause: Option<Box<dyn error::Error + Send + Sync>>,
+}
+
+#[cfg(all(feature = "std", not(target_env = "sgx")))]
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        self.code == other.code
+    }
+}
+
+#[cfg(all(feature = "std", not(target_env = "sgx")))]
+impl Eq for Error {}
+
 impl Error {
+    #[cfg(any(not(feature = "std"), target_env = "sgx"))]
+    const fn new(code: NonZeroU32) -> Error {
+        Error(code)
+    }
+
+    #[cfg(all(feature = "std", not(target_env = "sgx")))]
+    const fn new(code: NonZeroU32) -> Error {
+        Error { code, cause: None }
+    }
+
+    fn raw_code(&self) -> NonZeroU32 {
+        #[cfg(all(feature = "std", not(target_env = "sgx")))]
+        { self.code }
+        #[cfg(any(not(feature = "std"), target_env = "sgx"))]
+        { self.0 }
+    }
+
     /// Extract the error code.
-    /// 
+    ///
     /// This may equal one of the codes defined in this library or may be a
     /// system error code.
-    /// 
+    ///
     /// One may attempt to format this error via the `Display` implementation.
     pub fn code(&self) -> NonZeroU32 {
-        self.0
+        self.raw_code()
     }
-    
+
+    /// Construct an error from a custom code, in the
+    /// `CUSTOM_START..=(CUSTOM_START | u16::MAX as u32)` range.
+    ///
+    /// This is intended to be used by so-called "user" RNGs, which may want
+    /// to forward specific error codes to the caller. This code should not
+    /// collide with any of the [OS error codes](#method.raw_os_error) or
+    /// getrandom's own [internal error codes](crate::ERROR_UNKNOWN).
+    pub const fn from_custom(code: u16) -> Error {
+        // CUSTOM_START | code is always non-zero, no matter the value of
+        // `code`.
+        Error::new(unsafe {
+            NonZeroU32::new_unchecked(CUSTOM_START | code as u32)
+        })
+    }
+
+    /// Construct an [`Error`] with a chained cause.
+    ///
+    /// This is only available with the `std` feature enabled, since a
+    /// `no_std` build keeps `Error` at a fixed 4-byte size. It is also
+    /// unavailable on `sgx` targets, which lack the `std::error` module
+    /// regardless of the `std` feature.
+    #[cfg(all(feature = "std", not(target_env = "sgx")))]
+    pub fn with_cause<E>(code: NonZeroU32, cause: E) -> Error
+    where
+        E: Into<Box<dyn error::Error + Send + Sync>>,
+    {
+        Error { code, cause: Some(cause.into()) }
+    }
+
+    /// Take the cause out of this [`Error`], if one was attached via
+    /// [`Error::with_cause`] or a conversion from `std::io::Error`.
+    #[cfg(all(feature = "std", not(target_env = "sgx")))]
+    pub fn take_cause(&mut self) -> Option<Box<dyn error::Error + Send + Sync>> {
+        self.cause.take()
+    }
+
+    /// Indicates if this [`Error`] was constructed via [`Error::from_custom`].
+    pub fn is_custom(&self) -> bool {
+        self.raw_code().get() >= CUSTOM_START
+    }
+
+    /// Extract the raw OS error code (if this error came from the OS)
+    ///
+    /// This method is identical to `std::io::Error::raw_os_error()`, except
+    /// that it works in `no_std` contexts. If this method returns `None`,
+    /// the error value can still be formatted via the `Display`
+    /// implementation.
+    pub fn raw_os_error(&self) -> Option<i32> {
+        let code = self.raw_code().get();
+        if code < INTERNAL_START {
+            Some(code as i32)
+        } else {
+            None
+        }
+    }
+
     fn msg(&self) -> Option<&'static str> {
-        if let Some(msg) = super::error_msg_inner(self.0) {
+        let code = self.raw_code();
+        if let Some(msg) = super::error_msg_inner(code) {
             Some(msg)
         } else {
-            match *self {
-                ERROR_UNKNOWN => Some("getrandom: unknown error"),
-                ERROR_UNAVAILABLE => Some("getrandom: unavailable"),
-                _ => None
-            }
+            INTERNAL_ERRORS.iter()
+                .find(|(c, _)| *c == code.get())
+                .map(|(_, msg)| *msg)
         }
     }
 }
@@ -65,7 +224,7 @@ impl fmt::Debug for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self.msg() {
             Some(msg) => write!(f, "Error(\"{}\")", msg),
-            None => write!(f, "Error(0x{:08X})", self.0),
+            None => write!(f, "Error(0x{:08X})", self.raw_code()),
         }
     }
 }
@@ -74,49 +233,171 @@ impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
         match self.msg() {
             Some(msg) => write!(f, "{}", msg),
-            None => write!(f, "getrandom: unknown code 0x{:08X}", self.0),
+            None => write!(f, "getrandom: unknown code 0x{:08X}", self.raw_code()),
         }
     }
 }
 
 impl From<NonZeroU32> for Error {
     fn from(code: NonZeroU32) -> Self {
-        Error(code)
+        Error::new(code)
     }
 }
 
 #[cfg(not(target_env = "sgx"))]
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        err.raw_os_error()
-            .and_then(|code| NonZeroU32::new(code as u32))
-            .map(|code| Error(code))
-            // in practice this should never happen
-            .unwrap_or(ERROR_UNKNOWN)
+        let code = err.raw_os_error()
+            .filter(|code| (*code as u32) < INTERNAL_START)
+            .and_then(|code| NonZeroU32::new(code as u32));
+
+        match code {
+            #[cfg(feature = "std")]
+            Some(code) => Error::with_cause(code, err),
+            #[cfg(not(feature = "std"))]
+            Some(code) => Error::new(code),
+            // Not an OS errno (e.g. an `ErrorKind::Other` raised by a
+            // browser/Web-Crypto backend) — fall back to `ERROR_UNKNOWN`,
+            // but under `std` keep `err` around as the cause.
+            #[cfg(feature = "std")]
+            None => Error::with_cause(ERROR_UNKNOWN.code(), err),
+            #[cfg(not(feature = "std"))]
+            None => ERROR_UNKNOWN,
+        }
     }
 }
 
 #[cfg(not(target_env = "sgx"))]
 impl From<Error> for io::Error {
     fn from(err: Error) -> Self {
-        match err.msg() {
-            Some(msg) => io::Error::new(io::ErrorKind::Other, msg),
-            None => io::Error::from_raw_os_error(err.0.get() as i32),
+        #[cfg(feature = "std")]
+        let mut err = err;
+
+        // Prefer the original boxed cause, if one is attached, over
+        // rebuilding a fresh io::Error from just the code.
+        #[cfg(feature = "std")]
+        if let Some(cause) = err.take_cause() {
+            return match cause.downcast::<io::Error>() {
+                Ok(io_err) => *io_err,
+                Err(cause) => io::Error::new(io::ErrorKind::Other, cause),
+            };
+        }
+
+        match err.raw_os_error() {
+            Some(errno) => io::Error::from_raw_os_error(errno),
+            None => io::Error::new(io::ErrorKind::Other, err.msg().unwrap_or("getrandom: unknown error")),
         }
     }
 }
 
 #[cfg(not(target_env = "sgx"))]
-impl error::Error for Error { }
+impl error::Error for Error {
+    #[cfg(feature = "std")]
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        self.cause.as_ref().map(|cause| cause.as_ref() as &(dyn error::Error + 'static))
+    }
+}
 
 #[cfg(test)]
 mod tests {
-    use std::mem::size_of;
+    use std::io;
+    use std::num::NonZeroU32;
     use super::Error;
-    
+
+    #[cfg(any(not(feature = "std"), target_env = "sgx"))]
     #[test]
     fn test_size() {
+        use std::mem::size_of;
+
         assert_eq!(size_of::<Error>(), 4);
         assert_eq!(size_of::<Result<(), Error>>(), 4);
     }
+
+    #[test]
+    fn test_raw_os_error_in_range() {
+        let err = Error::from(io::Error::from_raw_os_error(13));
+        assert_eq!(err.raw_os_error(), Some(13));
+
+        // The largest code still below `INTERNAL_START` is a valid errno.
+        let max_errno = Error::from(NonZeroU32::new(0x7FFF_FFFF).unwrap());
+        assert_eq!(max_errno.raw_os_error(), Some(0x7FFF_FFFF));
+
+        // `INTERNAL_START` itself is the first internal code, not an errno.
+        let first_internal = Error::from(NonZeroU32::new(0x8000_0000).unwrap());
+        assert_eq!(first_internal.raw_os_error(), None);
+    }
+
+    #[test]
+    fn test_raw_os_error_none_for_internal_or_custom() {
+        assert_eq!(super::ERROR_UNKNOWN.raw_os_error(), None);
+        assert_eq!(super::ERROR_UNAVAILABLE.raw_os_error(), None);
+        assert_eq!(Error::from_custom(42).raw_os_error(), None);
+    }
+
+    #[test]
+    fn test_from_custom() {
+        let err = Error::from_custom(42);
+        assert!(err.is_custom());
+        assert_eq!(err.raw_os_error(), None);
+        assert_eq!(err.msg(), None);
+        assert!(err.code().get() >= super::CUSTOM_START);
+        assert_eq!(err.code().get(), super::CUSTOM_START | 42);
+    }
+
+    #[test]
+    fn test_non_custom_is_not_custom() {
+        assert!(!super::ERROR_UNKNOWN.is_custom());
+        assert!(!super::ERROR_UNAVAILABLE.is_custom());
+    }
+
+    #[test]
+    fn test_internal_error_messages() {
+        let cases = [
+            (super::ERROR_UNKNOWN, "getrandom: unknown error"),
+            (super::ERROR_UNAVAILABLE, "getrandom: unavailable"),
+            (super::ERROR_RDRAND_FAILURE, "getrandom: RDRAND failed after 10 retries"),
+            (super::ERROR_WEB_CRYPTO_UNAVAILABLE, "getrandom: Web Crypto API is unavailable"),
+            (super::ERROR_DEV_RANDOM_OPEN_FAILED, "getrandom: failed to open /dev/random"),
+            (super::ERROR_UNEXPECTED_SHORT_READ, "getrandom: unexpected short read"),
+            (super::ERROR_TOO_MANY_EINTR, "getrandom: too many retries after EINTR"),
+        ];
+        for (err, msg) in cases.iter() {
+            assert_eq!(format!("{}", err), *msg);
+        }
+    }
+
+    #[cfg(all(feature = "std", not(target_env = "sgx")))]
+    #[test]
+    fn test_with_cause_retains_cause() {
+        use std::error::Error as StdError;
+
+        let cause = io::Error::new(io::ErrorKind::Other, "boom");
+        let mut err = Error::with_cause(super::ERROR_UNAVAILABLE.code(), cause);
+        let source = StdError::source(&err).expect("cause should be retained");
+        assert_eq!(source.to_string(), "boom");
+
+        let cause = err.take_cause().expect("take_cause should return the cause");
+        assert_eq!(cause.to_string(), "boom");
+        assert!(err.take_cause().is_none());
+        assert!(StdError::source(&err).is_none());
+    }
+
+    #[cfg(all(feature = "std", not(target_env = "sgx")))]
+    #[test]
+    fn test_non_os_io_error_retains_cause() {
+        let io_err = io::Error::new(io::ErrorKind::Other, "web crypto missing");
+        let mut err = Error::from(io_err);
+        assert_eq!(err.raw_os_error(), None);
+        let cause = err.take_cause().expect("non-OS io::Error should retain its cause");
+        assert_eq!(cause.to_string(), "web crypto missing");
+    }
+
+    #[cfg(all(feature = "std", not(target_env = "sgx")))]
+    #[test]
+    fn test_io_error_round_trip_keeps_cause() {
+        let original = io::Error::new(io::ErrorKind::Other, "web crypto missing");
+        let err = Error::from(original);
+        let roundtripped: io::Error = err.into();
+        assert_eq!(roundtripped.to_string(), "web crypto missing");
+    }
 }
\ No newline at end of file